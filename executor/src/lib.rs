@@ -1,40 +1,81 @@
 #![forbid(unsafe_code)]
 
+use std::fmt;
 use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::pin::Pin;
-use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
 use std::task::{Context, Poll};
 
 use futures::channel::oneshot;
 use futures::task::{waker_ref, ArcWake};
 use pin_project::pin_project;
 
-struct Executor {
+/// Drives the ready queue built by [`Spawner::new`]. `run` blocks the calling
+/// thread and returns once the queue has drained and every sender into it is
+/// gone: every `Spawner` clone and every task (each task holds a sender to
+/// reschedule itself) has been dropped. [`Spawner::shutdown`] alone does not
+/// make it return.
+pub struct Runner {
     ready_queue: mpsc::Receiver<Arc<Task>>,
 }
 
-impl Executor {
-    fn run(self) {
+impl Runner {
+    pub fn run(self) {
         for task in self.ready_queue.into_iter() {
+            if task.state.load(Ordering::SeqCst) == Task::ABORTED {
+                task.drop_future();
+                continue;
+            }
+
             let waker = waker_ref(&task);
             let context = &mut Context::from_waker(&waker);
-            let _ = task.future.lock().unwrap().as_mut().poll(context);
+            let poll_result = catch_unwind(AssertUnwindSafe(|| {
+                task.future
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .as_mut()
+                    .poll(context)
+            }));
+
+            if poll_result.is_err() {
+                task.state.store(Task::PANICKED, Ordering::SeqCst);
+                task.drop_future();
+            }
         }
     }
 }
 
-pub struct Spawner {
+struct Shared {
     sender: mpsc::SyncSender<Arc<Task>>,
+    closed: AtomicBool,
+}
+
+#[derive(Clone)]
+pub struct Spawner {
+    shared: Arc<Shared>,
 }
 
 impl Spawner {
-    pub fn new() -> Self {
+    pub fn new() -> (Spawner, Runner) {
         const MAX_QUEUED_TASKS: usize = 10_000;
         let (sender, ready_queue) = mpsc::sync_channel(MAX_QUEUED_TASKS);
-        let executor = Executor { ready_queue };
-        std::thread::spawn(|| executor.run()); // TODO: add signal to kill the thread
+        let spawner = Spawner {
+            shared: Arc::new(Shared {
+                sender,
+                closed: AtomicBool::new(false),
+            }),
+        };
+        (spawner, Runner { ready_queue })
+    }
 
-        Spawner { sender }
+    /// Stops new tasks from being scheduled (a later `spawn` yields a handle
+    /// that resolves to [`JoinError::Aborted`]); tasks already spawned keep
+    /// running. `Runner::run` does not return until every `Spawner` clone and
+    /// every task has also been dropped.
+    pub fn shutdown(&self) {
+        self.shared.closed.store(true, Ordering::SeqCst);
     }
 
     pub fn spawn<T: Send + 'static>(
@@ -42,15 +83,21 @@ impl Spawner {
         future: impl Future<Output = T> + 'static + Send,
     ) -> Handle<T> {
         let (sender, receiver) = oneshot::channel();
-        let task = Task {
+        let task = Arc::new(Task {
             future: Mutex::new(Box::pin(SelfStoreFuture {
                 output: Some(sender),
                 future,
             })),
-            sender: self.sender.clone(),
-        };
-        self.sender.send(Arc::new(task)).unwrap();
-        Handle { receiver }
+            sender: self.shared.sender.clone(),
+            state: AtomicU8::new(Task::RUNNING),
+        });
+        if self.shared.closed.load(Ordering::SeqCst) {
+            task.state.store(Task::ABORTED, Ordering::SeqCst);
+            task.drop_future();
+        } else {
+            self.shared.sender.send(task.clone()).unwrap();
+        }
+        Handle { receiver, task }
     }
 }
 
@@ -58,6 +105,33 @@ struct Task {
     // TODO: 能否避免堆分配
     future: Mutex<Pin<Box<dyn Future<Output = ()> + Send>>>,
     sender: mpsc::SyncSender<Arc<Task>>,
+    state: AtomicU8,
+}
+
+impl Task {
+    const RUNNING: u8 = 0;
+    const ABORTED: u8 = 1;
+    const PANICKED: u8 = 2;
+
+    /// Flags the task as aborted and wakes it so the executor drops its
+    /// future the next time it is polled, without running it further.
+    fn abort(self: &Arc<Self>) {
+        if self
+            .state
+            .compare_exchange(Self::RUNNING, Self::ABORTED, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            let _ = self.sender.send(self.clone());
+        }
+    }
+
+    /// Replaces the task's future with a no-op one, dropping the original
+    /// (and, with it, the `SelfStoreFuture`'s oneshot sender) so the paired
+    /// `Handle` observes a closed channel.
+    fn drop_future(&self) {
+        let mut future = self.future.lock().unwrap_or_else(PoisonError::into_inner);
+        *future = Box::pin(std::future::pending());
+    }
 }
 
 impl ArcWake for Task {
@@ -66,17 +140,61 @@ impl ArcWake for Task {
     }
 }
 
+/// Why a [`Handle`] failed to produce its task's output.
+#[derive(Debug)]
+pub enum JoinError {
+    /// The task panicked while being polled.
+    Panicked,
+    /// The task was aborted via [`Handle::abort`].
+    Aborted,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panicked => write!(f, "task panicked"),
+            JoinError::Aborted => write!(f, "task was aborted"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
 #[pin_project]
 pub struct Handle<T> {
     #[pin]
     receiver: oneshot::Receiver<T>,
+    task: Arc<Task>,
+}
+
+impl<T> Handle<T> {
+    /// Stops the task from being polled again; its future is dropped the
+    /// next time the executor would have run it.
+    pub fn abort(&self) {
+        self.task.abort();
+    }
+
+    /// Lets the task keep running to completion in the background, without
+    /// keeping this handle around to observe its result.
+    pub fn detach(self) {
+        drop(self);
+    }
 }
 
 impl<T> Future for Handle<T> {
-    type Output = T;
+    type Output = Result<T, JoinError>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.project().receiver.poll(cx).map(Result::unwrap)
+        let this = self.project();
+        this.receiver.poll(cx).map(|result| {
+            result.map_err(|_canceled| {
+                if this.task.state.load(Ordering::SeqCst) == Task::ABORTED {
+                    JoinError::Aborted
+                } else {
+                    JoinError::Panicked
+                }
+            })
+        })
     }
 }
 
@@ -108,13 +226,47 @@ where
 mod test {
     use futures::executor::block_on;
 
-    use crate::Spawner;
+    use crate::{JoinError, Spawner};
 
     #[test]
     fn test() {
-        let spawner = Spawner::new();
+        let (spawner, runner) = Spawner::new();
+        std::thread::spawn(move || runner.run());
         let handle = spawner.spawn(async { 1 });
         let output = block_on(handle);
-        assert_eq!(output, 1)
+        assert_eq!(output.unwrap(), 1)
+    }
+
+    #[test]
+    fn test_abort() {
+        let (spawner, runner) = Spawner::new();
+        std::thread::spawn(move || runner.run());
+        let handle = spawner.spawn(std::future::pending::<()>());
+        handle.abort();
+        assert!(matches!(block_on(handle), Err(JoinError::Aborted)));
+    }
+
+    #[test]
+    fn test_panic() {
+        let (spawner, runner) = Spawner::new();
+        std::thread::spawn(move || runner.run());
+        let handle = spawner.spawn(async { panic!("boom") });
+        assert!(matches!(block_on(handle), Err(JoinError::Panicked)));
+    }
+
+    #[test]
+    fn test_shutdown_stops_new_tasks_and_drains_runner() {
+        let (spawner, runner) = Spawner::new();
+        let runner_thread = std::thread::spawn(move || runner.run());
+
+        let handle = spawner.spawn(async { 1 });
+        assert_eq!(block_on(handle).unwrap(), 1);
+
+        spawner.shutdown();
+        let rejected = spawner.spawn(async { 2 });
+        assert!(matches!(block_on(rejected), Err(JoinError::Aborted)));
+
+        drop(spawner);
+        runner_thread.join().unwrap();
     }
 }
@@ -0,0 +1,208 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use pin_project::pin_project;
+
+use crate::deterministic;
+use crate::spawn::{Handle, Spawner};
+use crate::timer::Timer;
+
+/// The result of racing two futures with [`select`]: which one finished
+/// first, and its output.
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+#[pin_project]
+pub struct Select<A, B> {
+    #[pin]
+    a: A,
+    #[pin]
+    b: B,
+}
+
+impl<A, B> Future for Select<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    type Output = Either<A::Output, B::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(output) = this.a.poll(cx) {
+            return Poll::Ready(Either::Left(output));
+        }
+        if let Poll::Ready(output) = this.b.poll(cx) {
+            return Poll::Ready(Either::Right(output));
+        }
+        Poll::Pending
+    }
+}
+
+/// Races `a` against `b`, resolving to whichever finishes first. The other
+/// future is simply dropped.
+pub fn select<A: Future, B: Future>(a: A, b: B) -> Select<A, B> {
+    Select { a, b }
+}
+
+/// Returned by [`timeout`] when `duration` elapses before the future does.
+#[derive(Debug)]
+pub struct TimedOut;
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "operation timed out")
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Runs `fut`, failing with [`TimedOut`] if it has not completed within
+/// `duration`.
+///
+/// The deadline is measured against the [`deterministic`] virtual clock when
+/// one is current on this thread, so `timeout` advances instantly under
+/// [`crate::deterministic::Deterministic`] instead of the real wall clock.
+pub async fn timeout<F: Future>(duration: Duration, fut: F) -> Result<F::Output, TimedOut> {
+    let now = match deterministic::current() {
+        Some(scheduler) => scheduler.now(),
+        None => Instant::now(),
+    };
+    match select(fut, Timer::new(now + duration)).await {
+        Either::Left(output) => Ok(output),
+        Either::Right(()) => Err(TimedOut),
+    }
+}
+
+/// A set of spawned tasks that are cancelled together, either explicitly via
+/// [`TaskGroup::cancel`] or implicitly when the group is dropped.
+pub struct TaskGroup {
+    spawner: Spawner,
+    children: Mutex<Vec<Handle<()>>>,
+}
+
+impl TaskGroup {
+    pub fn new(spawner: Spawner) -> Self {
+        Self {
+            spawner,
+            children: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Spawns `future` as a child of this group; its output is discarded and
+    /// it is aborted along with the rest of the group.
+    pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+        let handle = self.spawner.spawn(future);
+        self.children.lock().unwrap().push(handle);
+    }
+
+    /// Aborts every child task spawned into this group so far.
+    pub fn cancel(&self) {
+        for handle in self.children.lock().unwrap().drain(..) {
+            handle.abort();
+        }
+    }
+}
+
+impl Drop for TaskGroup {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::async_utils::{select, timeout, Either, TaskGroup, TimedOut};
+    use crate::block::block_on;
+    use crate::spawn::Spawner;
+
+    #[test]
+    fn test_select_returns_faster_side_and_drops_loser() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let loser = DropFlag(dropped.clone());
+        let winner = select(std::future::ready(1), async move {
+            let _loser = loser;
+            std::future::pending::<()>().await
+        });
+        match block_on(winner) {
+            Either::Left(output) => assert_eq!(output, 1),
+            Either::Right(()) => panic!("expected the ready future to win"),
+        }
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_timeout_succeeds_when_future_finishes_first() {
+        let result = block_on(timeout(Duration::from_secs(10), std::future::ready(1)));
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_timeout_fails_when_duration_elapses_first() {
+        let result = block_on(timeout(Duration::from_millis(10), std::future::pending::<()>()));
+        assert!(matches!(result, Err(TimedOut)));
+    }
+
+    #[test]
+    fn test_task_group_cancel_drops_pending_child() {
+        let (spawner, runner) = Spawner::new(2);
+        std::thread::spawn(move || runner.run());
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let group = TaskGroup::new(spawner);
+        let guard = DropFlag(dropped.clone());
+        group.spawn(async move {
+            let _guard = guard;
+            std::future::pending::<()>().await
+        });
+
+        // Give a worker a chance to poll the child at least once before it
+        // is cancelled, so the abort path (rather than a race with spawn)
+        // is what's under test.
+        std::thread::sleep(Duration::from_millis(50));
+        group.cancel();
+        std::thread::sleep(Duration::from_millis(50));
+
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_task_group_drop_aborts_pending_child() {
+        let (spawner, runner) = Spawner::new(2);
+        std::thread::spawn(move || runner.run());
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let group = TaskGroup::new(spawner);
+        let guard = DropFlag(dropped.clone());
+        group.spawn(async move {
+            let _guard = guard;
+            std::future::pending::<()>().await
+        });
+
+        std::thread::sleep(Duration::from_millis(50));
+        drop(group);
+        std::thread::sleep(Duration::from_millis(50));
+
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    /// Sets an `AtomicBool` on drop so tests can observe a future was
+    /// actually torn down rather than merely not polled further.
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+}
@@ -3,15 +3,17 @@ use std::sync::Arc;
 use std::task::{Context, Poll, Wake, Waker};
 
 use futures::pin_mut;
-use parking::{Parker, Unparker};
+
+use crate::park::{Parker, Unparker};
 
 struct Signal {
     unparker: Unparker,
 }
 
 impl Signal {
-    fn new() -> (Self, Parker) {
-        let (parker, unparker) = parking::pair();
+    fn new() -> (Self, Arc<Parker>) {
+        let parker = Parker::new();
+        let unparker = parker.unparker();
         (Self { unparker }, parker)
     }
 }
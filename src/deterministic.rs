@@ -0,0 +1,284 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use pin_project::pin_project;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::id::{Id, IdGenerator};
+use crate::timer::Timer;
+
+thread_local! {
+    // Set for the lifetime of the thread that created a `Deterministic`, so
+    // `Timer` can tell it should register with the virtual clock below
+    // instead of the process-wide reactor thread.
+    static CURRENT: RefCell<Option<Arc<Deterministic>>> = RefCell::new(None);
+}
+
+pub(crate) fn current() -> Option<Arc<Deterministic>> {
+    CURRENT.with(|cell| cell.borrow().clone())
+}
+
+struct VirtualClock {
+    base: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl VirtualClock {
+    fn new() -> Self {
+        Self {
+            base: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    fn now(&self) -> Instant {
+        self.base + *self.elapsed.lock().unwrap()
+    }
+
+    fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+struct PendingTimer {
+    id: Id<Timer>,
+    when: Instant,
+    waker: Waker,
+    fired: Arc<AtomicBool>,
+}
+
+struct DetTask {
+    future: Mutex<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    runnable: AtomicBool,
+}
+
+impl Wake for DetTask {
+    fn wake(self: Arc<Self>) {
+        self.runnable.store(true, Ordering::SeqCst);
+    }
+}
+
+/// A single-threaded, seed-reproducible executor for tests, following
+/// gpui's `Deterministic` executor: instead of a work-stealing pool it keeps
+/// one queue of runnable tasks and, on each step, uses a seeded [`StdRng`]
+/// to pick which one to poll next, so different seeds explore different
+/// interleavings reproducibly. It is paired with a [`VirtualClock`] that
+/// [`Timer`] registers with instead of the real reactor whenever one is
+/// current on the polling thread, so timer-based tests advance instantly
+/// via [`Deterministic::advance_clock`] rather than sleeping.
+pub struct Deterministic {
+    rng: Mutex<StdRng>,
+    tasks: Mutex<Vec<Arc<DetTask>>>,
+    clock: VirtualClock,
+    timers: Mutex<Vec<PendingTimer>>,
+    id_generator: IdGenerator,
+}
+
+impl Deterministic {
+    /// Builds a new executor seeded with `seed` and makes it current on the
+    /// calling thread; a captured failing seed can be passed back in here to
+    /// replay the exact same interleaving.
+    pub fn new(seed: u64) -> Arc<Self> {
+        let this = Arc::new(Self {
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+            tasks: Mutex::new(Vec::new()),
+            clock: VirtualClock::new(),
+            timers: Mutex::new(Vec::new()),
+            id_generator: IdGenerator::default(),
+        });
+        CURRENT.with(|cell| *cell.borrow_mut() = Some(this.clone()));
+        this
+    }
+
+    pub fn spawn<T: Send + 'static>(
+        self: &Arc<Self>,
+        future: impl Future<Output = T> + Send + 'static,
+    ) -> Handle<T> {
+        let (sender, receiver) = oneshot::channel();
+        let task = Arc::new(DetTask {
+            future: Mutex::new(Box::pin(SelfStoreFuture {
+                output: Some(sender),
+                future,
+            })),
+            runnable: AtomicBool::new(true),
+        });
+        self.tasks.lock().unwrap().push(task);
+        Handle { receiver }
+    }
+
+    /// Polls runnable tasks, picking the next one via the seeded RNG each
+    /// step, until none are left runnable (every task is either finished or
+    /// waiting on something, e.g. a [`Timer`] that hasn't elapsed yet).
+    pub fn run_until_parked(self: &Arc<Self>) {
+        loop {
+            let runnable: Vec<Arc<DetTask>> = self
+                .tasks
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|task| task.runnable.load(Ordering::SeqCst))
+                .cloned()
+                .collect();
+            if runnable.is_empty() {
+                break;
+            }
+            let index = self.rng.lock().unwrap().gen_range(0..runnable.len());
+            let task = runnable[index].clone();
+            task.runnable.store(false, Ordering::SeqCst);
+
+            let waker = Waker::from(task.clone());
+            let context = &mut Context::from_waker(&waker);
+            let done = task.future.lock().unwrap().as_mut().poll(context).is_ready();
+            if done {
+                self.tasks.lock().unwrap().retain(|t| !Arc::ptr_eq(t, &task));
+            }
+        }
+    }
+
+    /// The executor's current virtual time; used to construct timer
+    /// deadlines (e.g. `Timer::new(deterministic.now() + Duration::from_secs(3600))`)
+    /// without touching the real wall clock.
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Moves the virtual clock forward by `duration`, firing (and waking)
+    /// every timer whose deadline has now elapsed.
+    pub fn advance_clock(&self, duration: Duration) {
+        self.clock.advance(duration);
+        let now = self.clock.now();
+        let mut timers = self.timers.lock().unwrap();
+        let due: Vec<PendingTimer> = {
+            let mut kept = Vec::new();
+            let mut due = Vec::new();
+            for timer in timers.drain(..) {
+                if timer.when <= now {
+                    due.push(timer);
+                } else {
+                    kept.push(timer);
+                }
+            }
+            *timers = kept;
+            due
+        };
+        drop(timers);
+        for timer in due {
+            timer.fired.store(true, Ordering::SeqCst);
+            timer.waker.wake();
+        }
+    }
+
+    pub(crate) fn register_timer(&self, when: Instant, waker: Waker) -> (Id<Timer>, Arc<AtomicBool>) {
+        let id = self.id_generator.next();
+        let fired = Arc::new(AtomicBool::new(false));
+        self.timers.lock().unwrap().push(PendingTimer {
+            id,
+            when,
+            waker,
+            fired: fired.clone(),
+        });
+        (id, fired)
+    }
+
+    pub(crate) fn update_timer(&self, id: Id<Timer>, when: Instant, waker: Waker) {
+        let mut timers = self.timers.lock().unwrap();
+        if let Some(timer) = timers.iter_mut().find(|timer| timer.id == id) {
+            timer.when = when;
+            timer.waker = waker;
+        }
+    }
+
+    pub(crate) fn deregister_timer(&self, id: Id<Timer>) {
+        self.timers.lock().unwrap().retain(|timer| timer.id != id);
+    }
+}
+
+#[pin_project]
+pub struct Handle<T> {
+    #[pin]
+    receiver: oneshot::Receiver<T>,
+}
+
+impl<T> Future for Handle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().receiver.poll(cx).map(Result::unwrap)
+    }
+}
+
+#[pin_project]
+struct SelfStoreFuture<T, F> {
+    output: Option<oneshot::Sender<T>>,
+
+    #[pin]
+    future: F,
+}
+
+impl<T, F> Future for SelfStoreFuture<T, F>
+where
+    F: Future<Output = T>,
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.project();
+        this.future.poll(cx).map(|output| {
+            if let Some(sender) = this.output.take() {
+                sender.send(output).ok();
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+
+    use crate::deterministic::Deterministic;
+    use crate::timer::Timer;
+
+    #[test]
+    fn test_timer_advances_instantly() {
+        let det = Deterministic::new(42);
+        let when = det.now() + Duration::from_secs(3600);
+        let handle = det.spawn(async move {
+            Timer::new(when).await;
+            1
+        });
+        // First poll registers the timer; it's not due yet, so nothing else
+        // is runnable until the clock is advanced.
+        det.run_until_parked();
+        det.advance_clock(Duration::from_secs(3600));
+        det.run_until_parked();
+        assert_eq!(block_on(handle), 1);
+    }
+
+    #[test]
+    fn test_same_seed_reproduces_order() {
+        fn run_with_seed(seed: u64) -> Vec<u32> {
+            let det = Deterministic::new(seed);
+            let order = Arc::new(Mutex::new(Vec::new()));
+            for i in 0..10 {
+                let order = order.clone();
+                det.spawn(async move {
+                    order.lock().unwrap().push(i);
+                });
+            }
+            det.run_until_parked();
+            Arc::try_unwrap(order).unwrap().into_inner().unwrap()
+        }
+
+        assert_eq!(run_with_seed(7), run_with_seed(7));
+    }
+}
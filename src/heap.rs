@@ -15,22 +15,6 @@ impl<K, V> Default for OrderedMap<K, V> {
 }
 
 impl<K, V> OrderedMap<K, V> {
-    pub(crate) fn first_key(&self) -> Option<K>
-    where
-        K: Ord + Copy,
-    {
-        let guard = self.inner.read().unwrap();
-        guard.first_key_value().map(|pair| pair.0).copied()
-    }
-
-    pub(crate) fn pop_first(&self) -> Option<(K, V)>
-    where
-        K: Ord,
-    {
-        let mut guard = self.inner.write().unwrap();
-        guard.pop_first()
-    }
-
     pub(crate) fn insert(&self, key: K, value: V)
     where
         K: Ord,
@@ -39,22 +23,22 @@ impl<K, V> OrderedMap<K, V> {
         guard.insert(key, value);
     }
 
-    pub(crate) fn update(&self, key: K, update_value_fn: impl FnOnce(V) -> V)
+    pub(crate) fn delete<Q>(&self, key: &Q)
     where
-        K: Ord,
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
     {
         let mut guard = self.inner.write().unwrap();
-        if let Some(new) = guard.remove(&key).map(update_value_fn) {
-            guard.insert(key, new);
-        }
+        guard.remove(key);
     }
 
-    pub(crate) fn delete<Q>(&self, key: &Q)
+    pub(crate) fn get<Q>(&self, key: &Q) -> Option<V>
     where
         K: Borrow<Q> + Ord,
         Q: Ord + ?Sized,
+        V: Clone,
     {
-        let mut guard = self.inner.write().unwrap();
-        guard.remove(key);
+        let guard = self.inner.read().unwrap();
+        guard.get(key).cloned()
     }
 }
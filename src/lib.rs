@@ -0,0 +1,9 @@
+pub mod async_utils;
+pub mod block;
+pub mod deterministic;
+mod heap;
+mod id;
+mod park;
+pub mod reactor;
+pub mod spawn;
+pub mod timer;
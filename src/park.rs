@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+const EMPTY: usize = 0;
+const PARKED: usize = 1;
+const NOTIFIED: usize = 2;
+
+/// A thread parker built around an `EMPTY`/`PARKED`/`NOTIFIED` state machine
+/// instead of a bare condvar wait, so a wake arriving between a poll and the
+/// following `park()` call is coalesced into the `NOTIFIED` state rather
+/// than lost (which would otherwise block the parking thread until some
+/// unrelated later wake happened to arrive). Shared by [`crate::block::block_on`]
+/// and the timer reactor's deadline-based parking.
+pub(crate) struct Parker {
+    state: AtomicUsize,
+    mutex: Mutex<()>,
+    condvar: Condvar,
+}
+
+impl Parker {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            state: AtomicUsize::new(EMPTY),
+            mutex: Mutex::new(()),
+            condvar: Condvar::new(),
+        })
+    }
+
+    pub(crate) fn unparker(self: &Arc<Self>) -> Unparker {
+        Unparker(self.clone())
+    }
+
+    /// Blocks until the next [`Unparker::unpark`], or returns immediately if
+    /// one already arrived since the last `park`/`park_deadline` call.
+    pub(crate) fn park(&self) {
+        if self.state.swap(EMPTY, Ordering::SeqCst) == NOTIFIED {
+            return;
+        }
+
+        let mut guard = self.mutex.lock().unwrap();
+        if self.transition_to_parked() {
+            return;
+        }
+        while self.state.load(Ordering::SeqCst) == PARKED {
+            guard = self.condvar.wait(guard).unwrap();
+        }
+        drop(guard);
+        self.state.store(EMPTY, Ordering::SeqCst);
+    }
+
+    /// Like [`Parker::park`], but gives up once `deadline` passes.
+    pub(crate) fn park_deadline(&self, deadline: Instant) {
+        if self.state.swap(EMPTY, Ordering::SeqCst) == NOTIFIED {
+            return;
+        }
+
+        let mut guard = self.mutex.lock().unwrap();
+        if self.transition_to_parked() {
+            return;
+        }
+        while self.state.load(Ordering::SeqCst) == PARKED {
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            let (next_guard, timeout) = self.condvar.wait_timeout(guard, remaining).unwrap();
+            guard = next_guard;
+            if timeout.timed_out() {
+                break;
+            }
+        }
+        drop(guard);
+        self.state.store(EMPTY, Ordering::SeqCst);
+    }
+
+    /// Attempts `EMPTY` -> `PARKED`. Returns `true` (without blocking) if a
+    /// wake raced in and already flipped the state to `NOTIFIED` instead.
+    fn transition_to_parked(&self) -> bool {
+        if self
+            .state
+            .compare_exchange(EMPTY, PARKED, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            self.state.store(EMPTY, Ordering::SeqCst);
+            return true;
+        }
+        false
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Unparker(Arc<Parker>);
+
+impl Unparker {
+    pub(crate) fn unpark(&self) {
+        let previous = self.0.state.swap(NOTIFIED, Ordering::SeqCst);
+        if previous == PARKED {
+            let _guard = self.0.mutex.lock().unwrap();
+            self.0.condvar.notify_one();
+        }
+    }
+}
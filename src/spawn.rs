@@ -1,36 +1,85 @@
+use std::cell::RefCell;
+use std::fmt;
 use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::pin::Pin;
-use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::task::{Context, Poll, Wake, Waker};
 
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
 use futures::channel::oneshot;
+use parking::{Parker, Unparker};
 use pin_project::pin_project;
+use rand::Rng;
 
-struct Executor {
-    ready_queue: mpsc::Receiver<Arc<Task>>,
+thread_local! {
+    // Set for the lifetime of a worker thread so `Task::wake` can tell it is
+    // running on a worker and push back onto that worker's own deque instead
+    // of the (contended) global injector.
+    static LOCAL_QUEUE: RefCell<Option<Worker<Arc<Task>>>> = RefCell::new(None);
 }
 
-impl Executor {
-    fn run(self) {
-        for task in self.ready_queue.into_iter() {
-            let waker = Waker::from(task.clone());
-            let context = &mut Context::from_waker(&waker);
-            let _ = task.future.lock().unwrap().as_mut().poll(context);
+struct Shared {
+    injector: Injector<Arc<Task>>,
+    stealers: Vec<Stealer<Arc<Task>>>,
+    unparkers: Vec<Unparker>,
+    spawner_count: AtomicUsize,
+    closed: AtomicBool,
+}
+
+impl Shared {
+    fn unpark_all(&self) {
+        for unparker in &self.unparkers {
+            unparker.unpark();
         }
     }
 }
 
 pub struct Spawner {
-    sender: mpsc::SyncSender<Arc<Task>>,
+    shared: Arc<Shared>,
 }
 
 impl Spawner {
-    pub fn new(max_queued_tasks: usize) -> Self {
-        let (sender, ready_queue) = mpsc::sync_channel(max_queued_tasks);
-        let executor = Executor { ready_queue };
-        std::thread::spawn(|| executor.run()); // TODO: add signal to kill the thread
+    /// Builds the work-stealing queues but does not spawn any threads;
+    /// drive them by calling [`Runner::run`] on the returned `Runner`, which
+    /// runs worker 0 on the calling thread and spawns a thread for each of
+    /// the other workers.
+    pub fn new(workers: usize) -> (Spawner, Runner) {
+        assert!(workers > 0, "Spawner needs at least one worker thread");
+
+        let locals: Vec<Worker<Arc<Task>>> = (0..workers).map(|_| Worker::new_fifo()).collect();
+        let stealers = locals.iter().map(Worker::stealer).collect();
+        let (parkers, unparkers): (Vec<Parker>, Vec<Unparker>) =
+            (0..workers).map(|_| parking::pair()).unzip();
+
+        let shared = Arc::new(Shared {
+            injector: Injector::new(),
+            stealers,
+            unparkers,
+            spawner_count: AtomicUsize::new(1),
+            closed: AtomicBool::new(false),
+        });
+
+        let spawner = Spawner {
+            shared: shared.clone(),
+        };
+        let runner = Runner {
+            locals,
+            parkers,
+            shared,
+        };
+        (spawner, runner)
+    }
 
-        Spawner { sender }
+    /// Stops new tasks from being scheduled (a later `spawn` yields a handle
+    /// that resolves to [`JoinError::Aborted`]) and wakes idle workers so they
+    /// can notice there is nothing left to do. Tasks already queued are still
+    /// polled, but each worker exits as soon as it finds no queued task, so a
+    /// task that is waiting for a wake at that point is not polled again.
+    pub fn shutdown(&self) {
+        self.shared.closed.store(true, Ordering::SeqCst);
+        self.shared.unpark_all();
     }
 
     pub fn spawn<T: Send + 'static>(
@@ -38,41 +87,248 @@ impl Spawner {
         future: impl Future<Output = T> + 'static + Send,
     ) -> Handle<T> {
         let (sender, receiver) = oneshot::channel();
-        let task = Task {
+        let task = Arc::new(Task {
             future: Mutex::new(Box::pin(SelfStoreFuture {
                 output: Some(sender),
                 future,
             })),
-            sender: self.sender.clone(),
+            shared: self.shared.clone(),
+            state: AtomicU8::new(Task::RUNNING),
+        });
+        if self.shared.closed.load(Ordering::SeqCst) {
+            task.state.store(Task::ABORTED, Ordering::SeqCst);
+            task.drop_future();
+        } else {
+            self.shared.injector.push(task.clone());
+            self.shared.unpark_all();
+        }
+        Handle { receiver, task }
+    }
+}
+
+impl Clone for Spawner {
+    fn clone(&self) -> Self {
+        self.shared.spawner_count.fetch_add(1, Ordering::SeqCst);
+        Spawner {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for Spawner {
+    fn drop(&mut self) {
+        if self.shared.spawner_count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.shutdown();
+        }
+    }
+}
+
+/// Drives one worker pool built by [`Spawner::new`]. `run` keeps worker 0 on
+/// the calling thread and spawns the rest, returning once every `Spawner`
+/// clone has been dropped (or [`Spawner::shutdown`] called) and the queues
+/// have drained.
+pub struct Runner {
+    locals: Vec<Worker<Arc<Task>>>,
+    parkers: Vec<Parker>,
+    shared: Arc<Shared>,
+}
+
+impl Runner {
+    pub fn run(mut self) {
+        let mut join_handles = Vec::new();
+        while self.locals.len() > 1 {
+            let index = self.locals.len() - 1;
+            let local = self.locals.pop().unwrap();
+            let parker = self.parkers.pop().unwrap();
+            let shared = self.shared.clone();
+            join_handles.push(
+                std::thread::Builder::new()
+                    .name(format!("toy-async-worker-{index}"))
+                    .spawn(move || run_worker(index, local, parker, shared))
+                    .expect("failed to spawn worker thread"),
+            );
+        }
+
+        run_worker(0, self.locals.pop().unwrap(), self.parkers.pop().unwrap(), self.shared);
+
+        for join_handle in join_handles {
+            let _ = join_handle.join();
+        }
+    }
+}
+
+fn run_worker(index: usize, local: Worker<Arc<Task>>, parker: Parker, shared: Arc<Shared>) {
+    LOCAL_QUEUE.with(|cell| *cell.borrow_mut() = Some(local));
+    loop {
+        let task = LOCAL_QUEUE.with(|cell| {
+            let cell = cell.borrow();
+            find_task(cell.as_ref().unwrap(), &shared, index)
+        });
+        match task {
+            Some(task) => poll_task(&task),
+            None if shared.closed.load(Ordering::SeqCst) => break,
+            None => parker.park(),
+        }
+    }
+}
+
+fn find_task(local: &Worker<Arc<Task>>, shared: &Shared, index: usize) -> Option<Arc<Task>> {
+    if let Some(task) = local.pop() {
+        return Some(task);
+    }
+
+    loop {
+        match shared.injector.steal_batch_and_pop(local) {
+            Steal::Success(task) => return Some(task),
+            Steal::Retry => continue,
+            Steal::Empty => break,
+        }
+    }
+
+    if shared.stealers.len() > 1 {
+        let sibling = loop {
+            let candidate = rand::thread_rng().gen_range(0..shared.stealers.len());
+            if candidate != index {
+                break candidate;
+            }
         };
-        self.sender.send(Arc::new(task)).unwrap();
-        Handle { receiver }
+        if let Steal::Success(task) = shared.stealers[sibling].steal_batch_and_pop(local) {
+            return Some(task);
+        }
+    }
+
+    None
+}
+
+fn poll_task(task: &Arc<Task>) {
+    if task.state.load(Ordering::SeqCst) == Task::ABORTED {
+        task.drop_future();
+        return;
+    }
+    let waker = Waker::from(task.clone());
+    let context = &mut Context::from_waker(&waker);
+    let poll_result = catch_unwind(AssertUnwindSafe(|| {
+        task.future
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+            .poll(context)
+    }));
+    if poll_result.is_err() {
+        task.state.store(Task::PANICKED, Ordering::SeqCst);
+        task.drop_future();
     }
 }
 
 struct Task {
     // TODO: 能否避免堆分配
     future: Mutex<Pin<Box<dyn Future<Output = ()> + Send>>>,
-    sender: mpsc::SyncSender<Arc<Task>>,
+    shared: Arc<Shared>,
+    state: AtomicU8,
+}
+
+impl Task {
+    const RUNNING: u8 = 0;
+    const ABORTED: u8 = 1;
+    const PANICKED: u8 = 2;
+
+    /// Flags the task as aborted and wakes it so a worker drops its future
+    /// the next time it would have been polled, without running it further.
+    fn abort(self: &Arc<Self>) {
+        if self
+            .state
+            .compare_exchange(Self::RUNNING, Self::ABORTED, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            self.clone().schedule();
+        }
+    }
+
+    /// Replaces the task's future with a no-op one, dropping the original
+    /// (and, with it, the `SelfStoreFuture`'s oneshot sender) so the paired
+    /// `Handle` observes a closed channel.
+    fn drop_future(&self) {
+        let mut future = self.future.lock().unwrap_or_else(PoisonError::into_inner);
+        *future = Box::pin(std::future::pending());
+    }
+
+    fn schedule(self: Arc<Self>) {
+        let pushed_locally = LOCAL_QUEUE.with(|cell| {
+            if let Some(local) = cell.borrow().as_ref() {
+                local.push(self.clone());
+                true
+            } else {
+                false
+            }
+        });
+        if !pushed_locally {
+            self.shared.injector.push(self.clone());
+        }
+        self.shared.unpark_all();
+    }
 }
 
 impl Wake for Task {
     fn wake(self: Arc<Self>) {
-        self.sender.send(self.clone()).expect("send failed");
+        self.schedule();
     }
 }
 
+/// Why a [`Handle`] failed to produce its task's output.
+#[derive(Debug)]
+pub enum JoinError {
+    /// The task panicked while being polled.
+    Panicked,
+    /// The task was aborted via [`Handle::abort`].
+    Aborted,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panicked => write!(f, "task panicked"),
+            JoinError::Aborted => write!(f, "task was aborted"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
 #[pin_project]
 pub struct Handle<T> {
     #[pin]
     receiver: oneshot::Receiver<T>,
+    task: Arc<Task>,
+}
+
+impl<T> Handle<T> {
+    /// Stops the task from being polled again; its future is dropped the
+    /// next time a worker would have run it.
+    pub fn abort(&self) {
+        self.task.abort();
+    }
+
+    /// Lets the task keep running to completion in the background, without
+    /// keeping this handle around to observe its result.
+    pub fn detach(self) {
+        drop(self);
+    }
 }
 
 impl<T> Future for Handle<T> {
-    type Output = T;
+    type Output = Result<T, JoinError>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.project().receiver.poll(cx).map(Result::unwrap)
+        let this = self.project();
+        this.receiver.poll(cx).map(|result| {
+            result.map_err(|_canceled| {
+                if this.task.state.load(Ordering::SeqCst) == Task::ABORTED {
+                    JoinError::Aborted
+                } else {
+                    JoinError::Panicked
+                }
+            })
+        })
     }
 }
 
@@ -107,24 +363,64 @@ mod test {
     use async_std::task::sleep;
     use futures::executor::block_on;
 
-    use crate::spawn::Spawner;
+    use crate::spawn::{JoinError, Spawner};
 
     #[test]
     fn test_ready() {
-        let spawner = Spawner::new(10);
+        let (spawner, runner) = Spawner::new(4);
+        std::thread::spawn(move || runner.run());
         let handle = spawner.spawn(async { 1 });
         let output = block_on(handle);
-        assert_eq!(output, 1)
+        assert_eq!(output.unwrap(), 1)
     }
 
     #[test]
     fn test_sleep() {
-        let spawner = Spawner::new(10);
+        let (spawner, runner) = Spawner::new(4);
+        std::thread::spawn(move || runner.run());
         let handle = spawner.spawn(async {
             sleep(Duration::from_secs(1)).await;
             1
         });
         let output = block_on(handle);
-        assert_eq!(output, 1)
+        assert_eq!(output.unwrap(), 1)
+    }
+
+    #[test]
+    fn test_many_tasks_across_workers() {
+        let (spawner, runner) = Spawner::new(4);
+        std::thread::spawn(move || runner.run());
+        let handles: Vec<_> = (0..100).map(|i| spawner.spawn(async move { i })).collect();
+        let sum: i32 = handles.into_iter().map(block_on).map(Result::unwrap).sum();
+        assert_eq!(sum, (0..100).sum());
+    }
+
+    #[test]
+    fn test_shutdown_on_drop_drains_runner() {
+        let (spawner, runner) = Spawner::new(2);
+        let runner_thread = std::thread::spawn(move || runner.run());
+
+        let handle = spawner.spawn(async { 1 });
+        assert_eq!(block_on(handle).unwrap(), 1);
+
+        drop(spawner);
+        runner_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_abort_reports_join_error() {
+        let (spawner, runner) = Spawner::new(2);
+        std::thread::spawn(move || runner.run());
+        let handle = spawner.spawn(std::future::pending::<()>());
+        handle.abort();
+        assert!(matches!(block_on(handle), Err(JoinError::Aborted)));
+    }
+
+    #[test]
+    fn test_panic_reports_join_error() {
+        let (spawner, runner) = Spawner::new(2);
+        std::thread::spawn(move || runner.run());
+        let handle = spawner.spawn(async { panic!("boom") });
+        assert!(matches!(block_on(handle), Err(JoinError::Panicked)));
     }
 }
@@ -1,20 +1,21 @@
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll, Waker};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use once_cell::sync::Lazy;
-use parking::{Parker, Unparker};
 
-use crate::heap::OrderedMap;
+use crate::deterministic::{self, Deterministic};
 use crate::id::{Id, IdGenerator};
+use crate::park::{Parker, Unparker};
 
 static EVENT_SOURCE: Lazy<Arc<EventSource>> = Lazy::new(|| EventSource::try_new().unwrap());
 
 pub struct Timer {
-    handle: Option<Handle>,
+    handle: Option<TimerHandle>,
     when: Instant,
 }
 
@@ -26,8 +27,10 @@ impl Timer {
 
 impl Drop for Timer {
     fn drop(&mut self) {
-        if let Some(handle) = self.handle.take() {
-            EVENT_SOURCE.deregister(handle.id(), self.when)
+        match self.handle.take() {
+            Some(TimerHandle::Real(handle)) => EVENT_SOURCE.deregister(handle.id(), self.when),
+            Some(TimerHandle::Deterministic { id, scheduler, .. }) => scheduler.deregister_timer(id),
+            None => {}
         }
     }
 }
@@ -36,26 +39,176 @@ impl Future for Timer {
     type Output = ();
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        if let Some(id) = self.handle.as_ref().map(Handle::id) {
-            EVENT_SOURCE.update(id, self.when, cx.waker().clone());
-        } else {
-            self.handle = Some(EVENT_SOURCE.register(self.when, cx.waker().clone()));
-        }
-        if let Some(handle) = self.handle.as_ref() {
-            if handle.timeout.load(Ordering::SeqCst) {
-                Poll::Ready(())
-            } else {
-                Poll::Pending
+        match &self.handle {
+            None => {
+                self.handle = Some(match deterministic::current() {
+                    Some(scheduler) => {
+                        let (id, fired) = scheduler.register_timer(self.when, cx.waker().clone());
+                        TimerHandle::Deterministic { id, scheduler, fired }
+                    }
+                    None => TimerHandle::Real(EVENT_SOURCE.register(self.when, cx.waker().clone())),
+                });
+            }
+            Some(TimerHandle::Real(handle)) => {
+                EVENT_SOURCE.update(handle.id(), self.when, cx.waker().clone());
             }
+            Some(TimerHandle::Deterministic { id, scheduler, .. }) => {
+                scheduler.update_timer(*id, self.when, cx.waker().clone());
+            }
+        }
+        if self.handle.as_ref().unwrap().timed_out() {
+            Poll::Ready(())
         } else {
             Poll::Pending
         }
     }
 }
 
+enum TimerHandle {
+    Real(Handle),
+    Deterministic {
+        id: Id<Timer>,
+        scheduler: Arc<Deterministic>,
+        fired: Arc<AtomicBool>,
+    },
+}
+
+impl TimerHandle {
+    fn timed_out(&self) -> bool {
+        match self {
+            TimerHandle::Real(handle) => handle.timeout.load(Ordering::SeqCst),
+            TimerHandle::Deterministic { fired, .. } => fired.load(Ordering::SeqCst),
+        }
+    }
+}
+
+// Hierarchical timing wheel: level 0 covers the next `SLOTS_PER_LEVEL` ticks,
+// level 1's slots each cover `SLOTS_PER_LEVEL` level-0 ticks, and so on.
+// Insertion/removal/expiry are all O(1); only the (rare) cascade of a
+// higher-level slot down into finer ones costs work proportional to that
+// slot's timers.
+const TICK: Duration = Duration::from_millis(1);
+const SLOTS_PER_LEVEL: u64 = 64;
+const SLOT_BITS: u32 = 6; // log2(SLOTS_PER_LEVEL)
+const LEVELS: usize = 4;
+
+struct WheelEntry {
+    id: Id<Timer>,
+    when: Instant,
+    waker: ScheduledWaker,
+}
+
+/// Buckets keyed by `(level, slot)`, plus a hashmap from timer id to its
+/// current bucket so `remove` doesn't have to scan.
+struct Wheel {
+    base: Instant,
+    current_tick: AtomicU64,
+    levels: Vec<Vec<Mutex<Vec<WheelEntry>>>>,
+    index: Mutex<HashMap<Id<Timer>, (usize, usize)>>,
+}
+
+impl Wheel {
+    fn new(base: Instant) -> Self {
+        Self {
+            base,
+            current_tick: AtomicU64::new(0),
+            levels: (0..LEVELS)
+                .map(|_| (0..SLOTS_PER_LEVEL).map(|_| Mutex::new(Vec::new())).collect())
+                .collect(),
+            index: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn tick_for(&self, when: Instant) -> u64 {
+        (when.saturating_duration_since(self.base).as_millis() / TICK.as_millis()) as u64
+    }
+
+    /// Like `tick_for`, but rounds up so a timer is placed in the first tick
+    /// at or after its deadline rather than the tick immediately before it.
+    fn deadline_tick(&self, when: Instant) -> u64 {
+        let elapsed = when.saturating_duration_since(self.base).as_millis();
+        let tick_ms = TICK.as_millis();
+        ((elapsed + tick_ms - 1) / tick_ms) as u64
+    }
+
+    fn level_and_slot(tick: u64, current_tick: u64) -> (usize, usize) {
+        let delta = tick.saturating_sub(current_tick);
+        let mut level = 0;
+        while level + 1 < LEVELS && delta >= SLOTS_PER_LEVEL << (level as u32 * SLOT_BITS) {
+            level += 1;
+        }
+        let slot = ((tick >> (level as u32 * SLOT_BITS)) % SLOTS_PER_LEVEL) as usize;
+        (level, slot)
+    }
+
+    fn place(&self, id: Id<Timer>, when: Instant, waker: ScheduledWaker) {
+        let current_tick = self.current_tick.load(Ordering::SeqCst);
+        let tick = self.deadline_tick(when).max(current_tick);
+        let (level, slot) = Self::level_and_slot(tick, current_tick);
+        self.levels[level][slot]
+            .lock()
+            .unwrap()
+            .push(WheelEntry { id, when, waker });
+        self.index.lock().unwrap().insert(id, (level, slot));
+    }
+
+    /// The deadline of the earliest timer still scheduled anywhere in the
+    /// wheel, if any, so the caller can park until it actually elapses
+    /// instead of waking every tick to check.
+    fn next_deadline(&self) -> Option<Instant> {
+        self.levels
+            .iter()
+            .flatten()
+            .flat_map(|bucket| bucket.lock().unwrap().iter().map(|entry| entry.when).collect::<Vec<_>>())
+            .min()
+    }
+
+    fn remove(&self, id: Id<Timer>) -> Option<WheelEntry> {
+        let (level, slot) = self.index.lock().unwrap().remove(&id)?;
+        let mut bucket = self.levels[level][slot].lock().unwrap();
+        let position = bucket.iter().position(|entry| entry.id == id)?;
+        Some(bucket.remove(position))
+    }
+
+    /// Advances the wheel tick by tick up to (and including) `target_tick`,
+    /// cascading higher-level buckets down and calling `fire` for every
+    /// timer whose deadline has elapsed.
+    fn advance(&self, target_tick: u64, mut fire: impl FnMut(ScheduledWaker)) {
+        loop {
+            let tick = self.current_tick.load(Ordering::SeqCst);
+            if tick > target_tick {
+                break;
+            }
+
+            for level in 1..LEVELS {
+                let range = SLOTS_PER_LEVEL << ((level - 1) as u32 * SLOT_BITS);
+                if tick % range != 0 {
+                    continue;
+                }
+                let slot = ((tick / range) % SLOTS_PER_LEVEL) as usize;
+                let cascaded: Vec<WheelEntry> =
+                    std::mem::take(&mut *self.levels[level][slot].lock().unwrap());
+                for entry in cascaded {
+                    self.index.lock().unwrap().remove(&entry.id);
+                    self.place(entry.id, entry.when, entry.waker);
+                }
+            }
+
+            let slot = (tick % SLOTS_PER_LEVEL) as usize;
+            let due: Vec<WheelEntry> = std::mem::take(&mut *self.levels[0][slot].lock().unwrap());
+            for entry in due {
+                self.index.lock().unwrap().remove(&entry.id);
+                fire(entry.waker);
+            }
+
+            self.current_tick.store(tick + 1, Ordering::SeqCst);
+        }
+    }
+}
+
 struct EventSource {
     id_generator: IdGenerator,
-    scheduled: OrderedMap<(Instant, Id<Timer>), ScheduledWaker>,
+    wheel: Wheel,
     unparker: Unparker,
 }
 
@@ -67,7 +220,7 @@ impl EventSource {
             waker,
             notifier: notifier.clone(),
         };
-        self.scheduled.insert((when, id), scheduled_waker);
+        self.wheel.place(id, when, scheduled_waker);
         self.unparker.unpark();
         Handle {
             id,
@@ -76,21 +229,30 @@ impl EventSource {
     }
 
     fn update(&self, id: Id<Timer>, when: Instant, waker: Waker) {
-        self.scheduled
-            .update((when, id), |w| ScheduledWaker { waker, ..w });
+        if let Some(entry) = self.wheel.remove(id) {
+            self.wheel.place(
+                id,
+                when,
+                ScheduledWaker {
+                    waker,
+                    notifier: entry.waker.notifier,
+                },
+            );
+        }
         self.unparker.unpark();
     }
 
-    fn deregister(&self, id: Id<Timer>, when: Instant) {
-        self.scheduled.delete(&(when, id));
+    fn deregister(&self, id: Id<Timer>, _when: Instant) {
+        self.wheel.remove(id);
         self.unparker.unpark();
     }
 
     fn try_new() -> Result<Arc<Self>, std::io::Error> {
-        let (parker, unparker) = parking::pair();
+        let parker = Parker::new();
+        let unparker = parker.unparker();
         let this = Arc::new(Self {
             id_generator: IdGenerator::default(),
-            scheduled: OrderedMap::default(),
+            wheel: Wheel::new(Instant::now()),
             unparker,
         });
         let this_clone = this.clone();
@@ -100,24 +262,20 @@ impl EventSource {
         Ok(this)
     }
 
-    fn run(&self, parker: Parker) {
+    fn run(&self, parker: Arc<Parker>) {
         loop {
             let now = Instant::now();
-            if let Some(next_wake) = self.scheduled.first_key().map(|pair| pair.0) {
-                if next_wake > now {
-                    parker.park_deadline(next_wake);
-                } else {
-                    // TODO: when Rust has `drain` method on BTreeMap, replace the following code
-                    while let Some((when, _)) = self.scheduled.first_key() {
-                        if when > now {
-                            break;
-                        }
-                        let (_, waker) = self.scheduled.pop_first().unwrap();
-                        waker.wake();
-                    }
-                }
-            } else {
-                parker.park();
+            let target_tick = self.wheel.tick_for(now);
+            self.wheel.advance(target_tick, ScheduledWaker::wake);
+
+            match self.wheel.next_deadline() {
+                // Nothing scheduled: park until `register`/`update` unparks
+                // us, instead of busy-waking every tick for no reason.
+                None => parker.park(),
+                // Park until the nearest timer's actual deadline rather than
+                // just the next 1 ms tick, so a lone far-future timer doesn't
+                // wake the thread a thousand times a second.
+                Some(deadline) => parker.park_deadline(deadline.max(now)),
             }
         }
     }